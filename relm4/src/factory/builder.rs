@@ -1,16 +1,31 @@
-use super::{handle::FactoryHandle, DynamicIndex, FactoryComponent, FactoryView};
+use super::priority::PriorityInputQueue;
+use super::{
+    handle::FactoryHandle, DynamicIndex, FactoryComponent, FactoryLifecycleEvent, FactoryListener,
+    FactoryStatus, FactoryView,
+};
 
 use crate::{shutdown, OnDestroy, Receiver, Sender};
 
 use std::any;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::fmt;
 use std::rc::Rc;
+use std::time::Duration;
 
 use async_oneshot::oneshot;
 use futures::FutureExt;
 use tracing::info_span;
 
+/// Sent by a [`FactoryComponent`] from within its own `update_with_view` to
+/// ask its owning factory to remove it, e.g. in response to a "close"
+/// button inside a list row.
+///
+/// Carries the component's own [`DynamicIndex`] so the parent container
+/// knows which entry to detach once the component's service has finished
+/// tearing itself down.
+#[derive(Debug, Clone)]
+pub struct FactorySelfCommand(pub DynamicIndex);
+
 pub(super) struct FactoryBuilder<Widget, C, ParentMsg>
 where
     Widget: FactoryView,
@@ -23,12 +38,15 @@ where
     pub(super) input_rx: Receiver<C::Input>,
     pub(super) output_tx: Sender<C::Output>,
     pub(super) output_rx: Receiver<C::Output>,
+    pub(super) init_params: C::InitParams,
+    pub(super) grace_period: Duration,
 }
 
 impl<Widget, C, ParentMsg> FactoryBuilder<Widget, C, ParentMsg>
 where
     Widget: FactoryView,
     C: FactoryComponent<Widget, ParentMsg>,
+    C::InitParams: Clone,
     ParentMsg: 'static,
 {
     pub(super) fn new(index: &DynamicIndex, params: C::InitParams) -> Self {
@@ -38,7 +56,7 @@ where
         // Used by this component to send events to be handled externally by the caller.
         let (output_tx, output_rx) = crate::channel::<C::Output>();
 
-        let component = C::init_model(params, index, &input_tx, &output_tx);
+        let component = C::init_model(params.clone(), index, &input_tx, &output_tx);
         let root_widget = component.init_root();
 
         let data = Rc::new(RefCell::new(component));
@@ -50,15 +68,64 @@ where
             input_rx,
             output_tx,
             output_rx,
+            init_params: params,
+            grace_period: Duration::ZERO,
         }
     }
 
-    /// Starts the component, passing ownership to a future attached to a GLib context.
+    /// Queues a message for this component to process as soon as it starts.
+    ///
+    /// Safe to call any number of times between [`FactoryBuilder::new`] and
+    /// [`FactoryBuilder::start`]: messages simply sit in the input channel
+    /// until the component's service begins reading from it, and are then
+    /// processed in the order they were queued.
+    pub(super) fn queue_input(&self, message: C::Input) {
+        let _ = self.input_tx.send(message);
+    }
+
+    /// Sets how long outstanding commands are given to observe
+    /// [`ShutdownReceiver::wait`](crate::shutdown::ShutdownReceiver::wait)
+    /// and finish on their own before this component's service is torn down.
+    ///
+    /// Defaults to [`Duration::ZERO`], preserving the previous behavior of
+    /// cancelling outstanding commands immediately.
+    pub(super) fn with_grace_period(mut self, grace_period: Duration) -> Self {
+        self.grace_period = grace_period;
+        self
+    }
+
+    /// Starts the component eagerly: equivalent to calling
+    /// [`FactoryBuilder::start`] immediately. Kept as the default entry
+    /// point so existing call sites are unaffected by the split between
+    /// construction and starting.
     pub(super) fn launch<Transform>(
         self,
         index: &DynamicIndex,
         returned_widget: Widget::ReturnedWidget,
         parent_sender: &Sender<ParentMsg>,
+        parent_self_destruct: &Sender<DynamicIndex>,
+        transform: Transform,
+    ) -> FactoryHandle<Widget, C, ParentMsg>
+    where
+        Transform: Fn(C::Output) -> Option<ParentMsg> + 'static,
+    {
+        self.start(index, returned_widget, parent_sender, parent_self_destruct, transform)
+    }
+
+    /// Starts the component's service: spawns the task that drives
+    /// `update_with_view`, `update_cmd_with_view`, and `update_view`, and
+    /// begins forwarding its output to the parent.
+    ///
+    /// No message queued with [`FactoryBuilder::queue_input`], and no output
+    /// the component emits from `init_model`, is processed before this is
+    /// called - letting callers build a whole batch of factory rows, wire
+    /// them up, and start them in a controlled order.
+    pub(super) fn start<Transform>(
+        self,
+        index: &DynamicIndex,
+        returned_widget: Widget::ReturnedWidget,
+        parent_sender: &Sender<ParentMsg>,
+        parent_self_destruct: &Sender<DynamicIndex>,
         transform: Transform,
     ) -> FactoryHandle<Widget, C, ParentMsg>
     where
@@ -71,6 +138,8 @@ where
             input_rx,
             output_tx,
             output_rx,
+            init_params,
+            grace_period,
         } = self;
 
         let forward_sender = parent_sender.0.clone();
@@ -84,130 +153,413 @@ where
             }
         });
 
-        // Sends messages from commands executed from the background.
-        let (cmd_tx, cmd_rx) = crate::channel::<C::CommandOutput>();
-
-        // Gets notifications when a component's model and view is updated externally.
-        let (notifier, notifier_rx) = flume::bounded(0);
-
-        let mut widgets = data.borrow_mut().init_widgets(
+        // Shared with `FactoryHandle` so `status()` can report on the service
+        // without polling it, and so `restart()`/`terminate()` know what
+        // state the service is coming from.
+        let status = Rc::new(Cell::new(FactoryStatus::Running));
+
+        // Observers registered through `FactoryHandle::add_listener`.
+        let listeners: Rc<RefCell<Vec<Box<dyn FactoryListener>>>> =
+            Rc::new(RefCell::new(Vec::new()));
+
+        // Retained across restarts so that `Sender<C::Input>` handles handed
+        // out to the rest of the application keep working: the channel
+        // itself is never recreated, only the task reading from it.
+        let input_rx = Rc::new(RefCell::new(Some(input_rx)));
+
+        // Holds this component's `SourceId` once the service below has been
+        // spawned. Shared with the `on_destroy` handler so that whichever
+        // teardown path runs first - widget destruction or self-destruct -
+        // clears it, and the other does not try to remove it again.
+        let runtime_id: Rc<RefCell<Option<gtk::glib::SourceId>>> = Rc::new(RefCell::new(None));
+
+        // Holds the oneshot that will deliver this generation's `SourceId` to
+        // the `on_destroy` handler below. Rebuilt on every restart, since a
+        // oneshot can only be fired once, but the `on_destroy` closure itself
+        // is only ever registered here, for the lifetime of `root_widget`.
+        let burn_notifier: Rc<RefCell<Option<async_oneshot::Sender<gtk::glib::SourceId>>>> =
+            Rc::new(RefCell::new(None));
+
+        let (id, notifier, watch_tx) = spawn_service::<Widget, C, ParentMsg>(
             index,
+            data.clone(),
             &root_widget,
             &returned_widget,
-            &input_tx,
-            &output_tx,
+            input_tx.clone(),
+            input_rx.clone(),
+            output_tx.clone(),
+            parent_self_destruct.0.clone(),
+            runtime_id.clone(),
+            burn_notifier.clone(),
+            status.clone(),
+            listeners.clone(),
+            grace_period,
         );
 
-        // The source ID of the component's service will be sent through this once the root
-        // widget has been iced, which will give the component one last chance to say goodbye.
-        let (mut burn_notifier, burn_recipient) = oneshot::<gtk::glib::SourceId>();
-
-        // Notifies the component's child commands that it is now deceased.
-        let (death_notifier, death_recipient) = shutdown::channel();
-
-        let input_tx_ = input_tx.clone();
-        let runtime_data = data.clone();
-
-        // Spawns the component's service. It will receive both `Self::Input` and
-        // `Self::CommandOutput` messages. It will spawn commands as requested by
-        // updates, and send `Self::Output` messages externally.
-        let id = crate::spawn_local(async move {
-            let mut burn_notice = burn_recipient.fuse();
-            loop {
-                let notifier = notifier_rx.recv_async().fuse();
-                let cmd = cmd_rx.recv().fuse();
-                let input = input_rx.recv().fuse();
-
-                futures::pin_mut!(cmd);
-                futures::pin_mut!(input);
-                futures::pin_mut!(notifier);
-
-                futures::select!(
-                    // Performs the model update, checking if the update requested a command.
-                    // Runs that command asynchronously in the background using tokio.
-                    message = input => {
-                        if let Some(message) = message {
-                            let mut model = runtime_data.borrow_mut();
-
-                            let span = info_span!(
-                                "update_with_view",
-                                input=?message,
-                                component=any::type_name::<C>(),
-                                id=model.id(),
-                            );
-                            let _enter = span.enter();
-
-                            if let Some(command) = model.update_with_view(&mut widgets, message, &input_tx_, &output_tx)
-                            {
-                                let recipient = death_recipient.clone();
-                                crate::spawn(C::command(command, recipient, cmd_tx.clone()));
-                            }
-                        }
+        *runtime_id.borrow_mut() = Some(id);
+        let on_destroy_id = runtime_id.clone();
+        let on_destroy_burn = burn_notifier.clone();
+
+        // When the root widget is destroyed, the currently-running generation
+        // of the service will be removed.
+        let root_widget_ = root_widget.clone();
+        root_widget_.on_destroy(move || {
+            if let Some(id) = on_destroy_id.borrow_mut().take() {
+                if let Some(mut notify) = on_destroy_burn.borrow_mut().take() {
+                    let _ = notify.send(id);
+                }
+            }
+        });
+
+        // Give back a type for controlling the component service.
+        FactoryHandle {
+            data,
+            root_widget,
+            returned_widget,
+            input: input_tx,
+            output: output_tx,
+            notifier: Rc::new(RefCell::new(notifier)),
+            watch_tx: Rc::new(RefCell::new(watch_tx)),
+            runtime_id,
+            input_rx,
+            burn_notifier,
+            parent_self_destruct: parent_self_destruct.clone(),
+            init_params,
+            grace_period,
+            status,
+            listeners,
+        }
+    }
+}
+
+/// Runs a single generation of a factory component's service: builds its
+/// widgets, then spawns the `select!` loop that drives `update_with_view`,
+/// `update_cmd_with_view`, `update_view`, and teardown.
+///
+/// Shared by [`FactoryBuilder::launch`] (the first generation) and
+/// [`FactoryHandle::restart`] (every generation after), which is why the
+/// long-lived pieces - the input channel, the runtime id, and the burn
+/// notifier - are threaded in rather than created here. Returns the new
+/// generation's `SourceId`, its `Sender<()>` for externally-triggered
+/// view updates, and the watch sender that holds its latest projected state.
+///
+/// On a restart, `init_widgets` runs again against the *same* `root_widget`
+/// and `returned_widget` the first generation used - this function never
+/// tears down the previous generation's widget tree first. A
+/// `FactoryComponent::init_widgets` that unconditionally appends children
+/// (as most do) will therefore end up with duplicates after a restart unless
+/// its implementation clears out whatever it previously added before
+/// building the new widgets.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn spawn_service<Widget, C, ParentMsg>(
+    index: &DynamicIndex,
+    data: Rc<RefCell<C>>,
+    root_widget: &C::Root,
+    returned_widget: &Widget::ReturnedWidget,
+    input_tx: Sender<C::Input>,
+    input_rx: Rc<RefCell<Option<Receiver<C::Input>>>>,
+    output_tx: Sender<C::Output>,
+    parent_self_destruct: flume::Sender<DynamicIndex>,
+    runtime_id: Rc<RefCell<Option<gtk::glib::SourceId>>>,
+    burn_notifier: Rc<RefCell<Option<async_oneshot::Sender<gtk::glib::SourceId>>>>,
+    status: Rc<Cell<FactoryStatus>>,
+    listeners: Rc<RefCell<Vec<Box<dyn FactoryListener>>>>,
+    grace_period: Duration,
+) -> (
+    gtk::glib::SourceId,
+    Sender<()>,
+    tokio::sync::watch::Sender<C::State>,
+)
+where
+    Widget: FactoryView,
+    C: FactoryComponent<Widget, ParentMsg>,
+    ParentMsg: 'static,
+{
+    let mut widgets = data
+        .borrow_mut()
+        .init_widgets(index, root_widget, returned_widget, &input_tx, &output_tx);
+
+    let (notify_burn, burn_recipient) = oneshot::<gtk::glib::SourceId>();
+    *burn_notifier.borrow_mut() = Some(notify_burn);
+
+    // Retains only the most recent projected state; a late or idle observer
+    // always sees the current value instead of a backlog of stale ones.
+    let (watch_tx, _watch_rx) = tokio::sync::watch::channel(data.borrow().state());
+    let returned_watch_tx = watch_tx.clone();
+
+    // Notifies the component's child commands that it is now deceased.
+    let (death_notifier, death_recipient) = shutdown::channel();
+
+    // Gets notifications when a component's model and view is updated externally.
+    let (notifier, notifier_rx) = flume::bounded(0);
+
+    // Sends messages from commands executed from the background.
+    let (cmd_tx, cmd_rx) = crate::channel::<C::CommandOutput>();
+
+    // Counts commands spawned via `C::command` that haven't finished yet, so
+    // a burn can give them `grace_period` to observe `death_recipient`'s
+    // `ShutdownReceiver::wait()` and wrap up cleanly instead of being
+    // cancelled outright when their `AttachedShutdown` future is dropped.
+    // Incremented at spawn time and decremented when the spawned future
+    // itself completes - not when a `CommandOutput` is received, since
+    // `C::command` may report zero, one, or many outputs before it's done.
+    let (outstanding_tx, outstanding_rx) = tokio::sync::watch::channel(0u32);
+
+    // Lets the component end its own service from within `update_with_view`,
+    // e.g. in response to a "close" button inside the row it owns.
+    let (self_destruct_tx, self_destruct_rx) = crate::channel::<FactorySelfCommand>();
+
+    let input_tx_ = input_tx.clone();
+    let runtime_data = data;
+    let index_ = index.clone();
+
+    status.set(FactoryStatus::Running);
+    emit(&listeners, &index_, FactoryLifecycleEvent::Started);
+
+    // Spawns the component's service. It will receive both `Self::Input` and
+    // `Self::CommandOutput` messages. It will spawn commands as requested by
+    // updates, and send `Self::Output` messages externally.
+    let id = crate::spawn_local(async move {
+        let mut burn_notice = burn_recipient.fuse();
+        let mut input_rx_guard = input_rx.borrow_mut().take().expect(
+            "a factory component's input receiver should only be in use by one generation at a time",
+        );
+        let mut queue = PriorityInputQueue::<C::Input>::new();
+
+        loop {
+            // Opportunistically drain anything already buffered so a
+            // high-priority message (e.g. cancellation, resize) that arrived
+            // while this loop was busy jumps ahead of the rest.
+            while let Ok(message) = input_rx_guard.try_recv() {
+                queue.push(message);
+            }
+
+            // A message already sitting in `queue` is ready immediately, but
+            // still has to race fairly against `cmd`/`notifier`/
+            // `self_destruct`/`burn_notice` below rather than being handled
+            // outright - otherwise a sustained input producer keeps `queue`
+            // non-empty forever and those arms starve: `terminate`/
+            // `restart`/widget-destroy are never observed and command
+            // results never drain. `futures::select!` picks pseudo-randomly
+            // among whichever arms are ready, which is what keeps this fair.
+            let queued = async {
+                match queue.pop() {
+                    Some(message) => message,
+                    None => futures::future::pending().await,
+                }
+            }
+            .fuse();
+            let notifier = notifier_rx.recv_async().fuse();
+            let cmd = cmd_rx.recv().fuse();
+            let input = input_rx_guard.recv().fuse();
+            let self_destruct = self_destruct_rx.recv().fuse();
+
+            futures::pin_mut!(queued);
+            futures::pin_mut!(cmd);
+            futures::pin_mut!(input);
+            futures::pin_mut!(notifier);
+            futures::pin_mut!(self_destruct);
+
+            futures::select!(
+                // Highest-priority buffered message: handle it without
+                // waiting on the other arms this iteration.
+                message = queued => {
+                    let mut model = runtime_data.borrow_mut();
+
+                    let span = info_span!(
+                        "update_with_view",
+                        input=?message,
+                        component=any::type_name::<C>(),
+                        id=model.id(),
+                    );
+                    let _enter = span.enter();
+
+                    if let Some(command) = model.update_with_view(&mut widgets, message, &input_tx_, &output_tx, &self_destruct_tx)
+                    {
+                        let recipient = death_recipient.clone();
+                        let outstanding_tx = outstanding_tx.clone();
+                        outstanding_tx.send_modify(|outstanding| *outstanding += 1);
+
+                        // Decrement when the command future itself finishes,
+                        // not when a `CommandOutput` is received: `C::command`
+                        // may report zero, one, or many outputs before it's
+                        // actually done, so tracking "still outstanding" by
+                        // output count either blocks a grace-drain forever
+                        // (a side-effect-only command with no output) or lets
+                        // it return early and cancel a still-running command
+                        // (a streaming one that reports progress).
+                        let command = C::command(command, recipient, cmd_tx.clone());
+                        crate::spawn(async move {
+                            command.await;
+                            outstanding_tx.send_modify(|outstanding| *outstanding = outstanding.saturating_sub(1));
+                        });
                     }
 
-                    // Handles responses from a command.
-                    message = cmd => {
-                        if let Some(message) = message {
-                            let mut model = runtime_data.borrow_mut();
+                    watch_tx.send_if_modified(|state| {
+                        *state = model.state();
+                        true
+                    });
 
-                            let span = info_span!(
-                                "update_cmd_with_view",
-                                cmd_output=?message,
-                                component=any::type_name::<C>(),
-                                id=model.id(),
-                            );
-                            let _enter = span.enter();
+                    drop(_enter);
+                    emit(&listeners, &index_, FactoryLifecycleEvent::UpdatedModel);
+                }
 
-                            model.update_cmd_with_view(&mut widgets, message, &input_tx_, &output_tx);
-                        }
+                // Only buffers the message; it is popped from the priority
+                // queue and processed by the `queued` arm above.
+                message = input => {
+                    if let Some(message) = message {
+                        queue.push(message);
                     }
+                }
+
+                // Handles responses from a command.
+                message = cmd => {
+                    if let Some(message) = message {
+                        let mut model = runtime_data.borrow_mut();
+
+                        let span = info_span!(
+                            "update_cmd_with_view",
+                            cmd_output=?message,
+                            component=any::type_name::<C>(),
+                            id=model.id(),
+                        );
+                        let _enter = span.enter();
+
+                        model.update_cmd_with_view(&mut widgets, message, &input_tx_, &output_tx);
 
-                    // Triggered when the model and view have been updated externally.
-                    _ = notifier => {
-                        let model = runtime_data.borrow_mut();
-                        model.update_view(&mut widgets, &input_tx_, &output_tx);
+                        watch_tx.send_if_modified(|state| {
+                            *state = model.state();
+                            true
+                        });
+
+                        drop(_enter);
+                        emit(&listeners, &index_, FactoryLifecycleEvent::CommandCompleted);
                     }
+                }
+
+                // Triggered when the model and view have been updated externally.
+                _ = notifier => {
+                    let model = runtime_data.borrow_mut();
+                    model.update_view(&mut widgets, &input_tx_, &output_tx);
 
-                    // Triggered when the component is destroyed
-                    id = burn_notice => {
+                    watch_tx.send_if_modified(|state| {
+                        *state = model.state();
+                        true
+                    });
+                }
+
+                // Triggered when the component asked to remove itself, e.g. in
+                // response to a "close" action handled in `update_with_view`.
+                // By the time this arm runs, the `runtime_data.borrow_mut()`
+                // taken for that update has already been released, so this
+                // cannot deadlock against it.
+                command = self_destruct => {
+                    if let Some(FactorySelfCommand(index)) = command {
                         let mut model = runtime_data.borrow_mut();
 
                         model.shutdown(&mut widgets, output_tx);
 
                         death_notifier.shutdown();
+                        status.set(FactoryStatus::Terminated);
+                        emit(&listeners, &index_, FactoryLifecycleEvent::ShutDown);
+                        drop(model);
+
+                        await_command_drain(grace_period, outstanding_rx.clone()).await;
 
-                        if let Ok(id) = id {
+                        // Clear the shared runtime id ourselves so the
+                        // `on_destroy` handler does not remove it again.
+                        if let Some(id) = runtime_id.borrow_mut().take() {
                             id.remove();
                         }
 
+                        // Hand the input receiver back, same as the burn arm
+                        // below: a self-destructed component's handle is
+                        // normally dropped once the parent observes
+                        // `parent_self_destruct`, but until then a stray
+                        // `restart()` call should find `input_rx` available
+                        // rather than panic.
+                        *input_rx.borrow_mut() = Some(input_rx_guard);
+
+                        let _ = parent_self_destruct.send(index);
+
                         return
                     }
-                );
-            }
-        });
+                }
 
-        // Clone runtime id to be able to drop the runtime manually
-        // when the data is removed from the factory.
-        let runtime_id = Rc::new(RefCell::new(Some(id)));
-        let on_destroy_id = runtime_id.clone();
+                // Triggered when the component is destroyed, or when `terminate()`
+                // / `restart()` asked this generation's service to shut down.
+                id = burn_notice => {
+                    let mut model = runtime_data.borrow_mut();
 
-        // When the root widget is destroyed, the spawned service will be removed.
-        let root_widget_ = root_widget.clone();
-        root_widget_.on_destroy(move || {
-            if let Some(id) = on_destroy_id.borrow_mut().take() {
-                let _ = burn_notifier.send(id);
-            }
-        });
+                    model.shutdown(&mut widgets, output_tx);
 
-        // Give back a type for controlling the component service.
-        FactoryHandle {
-            data,
-            root_widget,
-            returned_widget,
-            input: input_tx,
-            notifier: Sender(notifier),
-            runtime_id,
+                    death_notifier.shutdown();
+                    status.set(FactoryStatus::Terminated);
+                    emit(&listeners, &index_, FactoryLifecycleEvent::ShutDown);
+                    drop(model);
+
+                    await_command_drain(grace_period, outstanding_rx.clone()).await;
+
+                    runtime_id.borrow_mut().take();
+
+                    if let Ok(id) = id {
+                        id.remove();
+                    }
+
+                    // Hand the input receiver back so a future `restart()` can
+                    // pick it up without invalidating existing `Sender`s.
+                    *input_rx.borrow_mut() = Some(input_rx_guard);
+
+                    return
+                }
+            );
         }
+    });
+
+    (id, Sender(notifier), returned_watch_tx)
+}
+
+fn emit(
+    listeners: &Rc<RefCell<Vec<Box<dyn FactoryListener>>>>,
+    index: &DynamicIndex,
+    event: FactoryLifecycleEvent,
+) {
+    for listener in listeners.borrow_mut().iter_mut() {
+        listener.on_event(index, event);
+    }
+}
+
+/// Waits for outstanding commands to finish, up to `grace_period`.
+///
+/// Called after `death_recipient`'s shutdown has already been signalled, so
+/// any outstanding command observing [`ShutdownReceiver::wait`](crate::shutdown::ShutdownReceiver::wait)
+/// has a bounded window to wrap up on its own before the caller removes the
+/// `SourceId` and drops `runtime_data` out from under it. A zero grace
+/// period (the default) returns immediately, preserving the previous
+/// behavior of cancelling outstanding commands outright.
+///
+/// `outstanding` is decremented by the spawned command future itself once it
+/// completes (see the `queued` arm above), not by anything reading `cmd_rx`,
+/// so this only has to watch it reach 0 - it doesn't need to touch `cmd_rx`.
+async fn await_command_drain(grace_period: Duration, mut outstanding: tokio::sync::watch::Receiver<u32>) {
+    if grace_period.is_zero() || *outstanding.borrow() == 0 {
+        return;
+    }
+
+    let drained = async {
+        while *outstanding.borrow() > 0 {
+            if outstanding.changed().await.is_err() {
+                break;
+            }
+        }
+    }
+    .fuse();
+    let timeout = gtk::glib::timeout_future(grace_period).fuse();
+
+    futures::pin_mut!(drained, timeout);
+    futures::select! {
+        () = drained => {},
+        () = timeout => {},
     }
 }
 
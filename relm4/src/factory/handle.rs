@@ -0,0 +1,193 @@
+use super::builder::spawn_service;
+use super::state_watch::StateReceiver;
+use super::{DynamicIndex, FactoryComponent, FactoryListener, FactoryView};
+
+use crate::Sender;
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+
+/// Current run state of a factory component's service, as reported by
+/// [`FactoryHandle::status`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FactoryStatus {
+    /// The service is running and processing input.
+    Running,
+    /// The service has shut down, whether through widget destruction,
+    /// self-destruct, or [`FactoryHandle::terminate`].
+    Terminated,
+}
+
+/// Handle to a running factory component, held by its owning factory
+/// container.
+///
+/// Dropping the widget at this handle's position destroys its service; the
+/// `runtime_id` is shared with the running service so that whichever path
+/// tears it down first (widget destruction, or the component itself)
+/// clears it, preventing the other from trying to remove it again.
+pub struct FactoryHandle<Widget, C, ParentMsg>
+where
+    Widget: FactoryView,
+    C: FactoryComponent<Widget, ParentMsg>,
+    ParentMsg: 'static,
+{
+    pub(super) data: Rc<RefCell<C>>,
+    pub(super) root_widget: C::Root,
+    pub(super) returned_widget: Widget::ReturnedWidget,
+    pub(super) input: Sender<C::Input>,
+    pub(super) output: Sender<C::Output>,
+    /// Wrapped in a cell so `restart` can swap it in once the next
+    /// generation is actually up, from a task it spawns rather than `&mut
+    /// self` directly.
+    pub(super) notifier: Rc<RefCell<Sender<()>>>,
+    /// Replaced on every restart, so `subscribe_state` always subscribes to
+    /// the currently-running generation. Wrapped in a cell for the same
+    /// reason as `notifier`.
+    pub(super) watch_tx: Rc<RefCell<tokio::sync::watch::Sender<C::State>>>,
+    /// Cleared by whichever teardown path (widget destruction or
+    /// self-destruct) runs first, so the other does not remove the
+    /// `SourceId` a second time.
+    pub(super) runtime_id: Rc<RefCell<Option<gtk::glib::SourceId>>>,
+    /// Retained across generations so `restart` can hand it back to a new
+    /// service loop without invalidating `Sender<C::Input>`s already handed
+    /// out to the rest of the application.
+    pub(super) input_rx: Rc<RefCell<Option<crate::Receiver<C::Input>>>>,
+    /// Holds the current generation's burn oneshot, so the `on_destroy`
+    /// handler registered once in `launch` always fires the right one.
+    pub(super) burn_notifier: Rc<RefCell<Option<async_oneshot::Sender<gtk::glib::SourceId>>>>,
+    pub(super) parent_self_destruct: Sender<DynamicIndex>,
+    /// Kept so `restart` can re-run `C::init_model` with the same arguments
+    /// the component was originally built with.
+    pub(super) init_params: C::InitParams,
+    /// How long the next generation's outstanding commands are given to
+    /// observe a shutdown and finish on their own before being torn down.
+    pub(super) grace_period: Duration,
+    pub(super) status: Rc<Cell<FactoryStatus>>,
+    pub(super) listeners: Rc<RefCell<Vec<Box<dyn FactoryListener>>>>,
+}
+
+impl<Widget, C, ParentMsg> FactoryHandle<Widget, C, ParentMsg>
+where
+    Widget: FactoryView,
+    C: FactoryComponent<Widget, ParentMsg>,
+    ParentMsg: 'static,
+{
+    /// Reports whether this component's service is currently running.
+    pub fn status(&self) -> FactoryStatus {
+        self.status.get()
+    }
+
+    /// Shuts the component's service down, the same way destroying its root
+    /// widget would. Does nothing if the service has already terminated.
+    pub fn terminate(&mut self) {
+        if let Some(mut notify) = self.burn_notifier.borrow_mut().take() {
+            if let Some(id) = self.runtime_id.borrow_mut().take() {
+                let _ = notify.send(id);
+            }
+        }
+    }
+
+    /// Registers a [`FactoryListener`] to observe this component's lifecycle
+    /// events, such as restarts or command completions.
+    pub fn add_listener(&self, listener: Box<dyn FactoryListener>) {
+        self.listeners.borrow_mut().push(listener);
+    }
+
+    /// Subscribes to this component's projected [`FactoryComponent::State`].
+    ///
+    /// The returned [`StateReceiver`] yields the current state immediately
+    /// on its first `recv().await`, and the latest state thereafter,
+    /// coalescing any updates it missed while not awaiting.
+    pub fn subscribe_state(&self) -> StateReceiver<C::State>
+    where
+        C::State: Clone,
+    {
+        StateReceiver::new(self.watch_tx.borrow().subscribe())
+    }
+}
+
+impl<Widget, C, ParentMsg> FactoryHandle<Widget, C, ParentMsg>
+where
+    Widget: FactoryView,
+    C: FactoryComponent<Widget, ParentMsg>,
+    C::InitParams: Clone,
+    Widget::ReturnedWidget: Clone,
+    ParentMsg: 'static,
+{
+    /// Tears down the current generation of this component's service (if any
+    /// is still running) and starts a fresh one at the same
+    /// [`DynamicIndex`], re-running `C::init_model` and `init_widgets`.
+    ///
+    /// The `Sender<C::Input>` and `Sender<C::Output>` that external code
+    /// already holds keep working across the restart: only the model, its
+    /// widgets, and the task driving them are replaced.
+    ///
+    /// `init_widgets` is re-run against the *same* `root_widget` and
+    /// `returned_widget` as the generation it replaces - this does not clear
+    /// their previous children first. A `FactoryComponent` whose
+    /// `init_widgets` unconditionally appends children must clear out
+    /// whatever it added last time itself, or a restarted row will end up
+    /// with duplicate widgets.
+    ///
+    /// The outgoing generation only hands `input_rx` back, clears
+    /// `runtime_id`, and returns from its own task once its burn arm has
+    /// actually run - which happens on a later turn of the GLib main
+    /// context, not synchronously with the [`FactoryHandle::terminate`] call
+    /// below. Spawning the next generation immediately would race that and
+    /// could observe `input_rx` still taken, so this defers the respawn
+    /// until the receiver reappears instead of assuming it already has.
+    pub fn restart(&mut self, index: &DynamicIndex) {
+        self.terminate();
+
+        let index = index.clone();
+        let data = self.data.clone();
+        let root_widget = self.root_widget.clone();
+        let returned_widget = self.returned_widget.clone();
+        let input = self.input.clone();
+        let output = self.output.clone();
+        let input_rx = self.input_rx.clone();
+        let parent_self_destruct = self.parent_self_destruct.clone();
+        let runtime_id = self.runtime_id.clone();
+        let burn_notifier = self.burn_notifier.clone();
+        let status = self.status.clone();
+        let listeners = self.listeners.clone();
+        let init_params = self.init_params.clone();
+        let grace_period = self.grace_period;
+        let notifier_cell = self.notifier.clone();
+        let watch_tx_cell = self.watch_tx.clone();
+
+        crate::spawn_local(async move {
+            // Poll rather than block: the thing we're waiting on - the
+            // previous generation's burn arm - only gets to run by being
+            // polled on this same main context, so yielding back to it is
+            // what lets that progress happen at all.
+            while input_rx.borrow().is_none() {
+                gtk::glib::timeout_future(Duration::ZERO).await;
+            }
+
+            let component = C::init_model(init_params, &index, &input, &output);
+            *data.borrow_mut() = component;
+
+            let (id, notifier, watch_tx) = spawn_service::<Widget, C, ParentMsg>(
+                &index,
+                data,
+                &root_widget,
+                &returned_widget,
+                input,
+                input_rx,
+                output,
+                parent_self_destruct.0.clone(),
+                runtime_id.clone(),
+                burn_notifier,
+                status,
+                listeners,
+                grace_period,
+            );
+
+            *runtime_id.borrow_mut() = Some(id);
+            *notifier_cell.borrow_mut() = notifier;
+            *watch_tx_cell.borrow_mut() = watch_tx;
+        });
+    }
+}
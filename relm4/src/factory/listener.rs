@@ -0,0 +1,30 @@
+use super::DynamicIndex;
+
+/// Lifecycle events emitted by a factory component's service loop, in the
+/// order they can occur: a component may see many `UpdatedModel` and
+/// `CommandCompleted` events between a `Started` and its eventual `ShutDown`,
+/// and `Started` again if the component is [restarted](super::FactoryHandle::restart).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FactoryLifecycleEvent {
+    /// The component's service has (re)started.
+    Started,
+    /// `update_with_view` ran and updated the model.
+    UpdatedModel,
+    /// A command spawned by this component reported an output and
+    /// `update_cmd_with_view` ran. Emitted once per output, so a command may
+    /// produce this zero, one, or many times.
+    CommandCompleted,
+    /// The component's service has shut down, whether through widget
+    /// destruction, self-destruct, or [`FactoryHandle::terminate`](super::FactoryHandle::terminate).
+    ShutDown,
+}
+
+/// Observes the lifecycle of a factory component, registered through
+/// [`FactoryHandle::add_listener`](super::FactoryHandle::add_listener).
+///
+/// This gives callers a way to implement crash-recovery and observability
+/// over long-lived factory children without polling.
+pub trait FactoryListener {
+    /// Called whenever the component's service emits a lifecycle event.
+    fn on_event(&mut self, index: &DynamicIndex, event: FactoryLifecycleEvent);
+}
@@ -0,0 +1,175 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+/// Scheduling priority for a message submitted to a factory component's
+/// input channel. Higher values are handled first; [`DEFAULT_PRIORITY`] is
+/// what a plain [`Sender::send`](crate::Sender::send) uses.
+pub type Priority = u8;
+
+/// The priority a plain `send` is handled at, so components that never opt
+/// into prioritized input see the same FIFO ordering as before.
+pub const DEFAULT_PRIORITY: Priority = 0;
+
+/// Gives a message type a scheduling priority, letting a factory component's
+/// service jump a high-priority message (e.g. cancellation, resize) ahead of
+/// a backlog of lower-priority ones.
+///
+/// Only [`PrioritizedInput`] implements this. There is deliberately no
+/// blanket `impl<T> WithPriority for T` - it would overlap the one on
+/// `PrioritizedInput<T>` and is unnecessary anyway, since [`priority_of`]
+/// already falls back to [`DEFAULT_PRIORITY`] for any `Input` type that
+/// doesn't implement this trait, without requiring it to.
+pub trait WithPriority {
+    /// This message's scheduling priority. Defaults to [`DEFAULT_PRIORITY`].
+    fn priority(&self) -> Priority {
+        DEFAULT_PRIORITY
+    }
+}
+
+/// Reads a message's scheduling priority for [`PriorityInputQueue`], without
+/// requiring its type to implement [`WithPriority`].
+///
+/// A blanket `impl<T> WithPriority for T` can't coexist with the specific
+/// `impl WithPriority for PrioritizedInput<T>` (coherence forbids the
+/// overlap), so a factory component whose `Input` never opts into priority
+/// scheduling would otherwise fail to compile against a `WithPriority` bound.
+/// This sidesteps that: it uses autoref to prefer `T::priority` when `T:
+/// WithPriority`, and falls back to [`DEFAULT_PRIORITY`] for every other
+/// `T`, all on stable Rust.
+pub(super) fn priority_of<T>(message: &T) -> Priority {
+    trait Fallback {
+        fn priority_of_fallback(&self) -> Priority {
+            DEFAULT_PRIORITY
+        }
+    }
+    impl<T> Fallback for T {}
+
+    struct Probe<'a, T>(&'a T);
+    impl<T: WithPriority> Probe<'_, T> {
+        fn priority_of_fallback(&self) -> Priority {
+            self.0.priority()
+        }
+    }
+
+    Probe(message).priority_of_fallback()
+}
+
+/// A small binary-heap-backed queue used by a factory component's service
+/// loop to pop its highest-priority buffered input first. Within equal
+/// priority, insertion order is preserved via a monotonically increasing
+/// sequence number used as the heap tiebreaker.
+#[derive(Debug)]
+pub(super) struct PriorityInputQueue<T> {
+    heap: BinaryHeap<Entry<T>>,
+    next_sequence: u64,
+}
+
+impl<T> PriorityInputQueue<T> {
+    pub(super) fn new() -> Self {
+        Self {
+            heap: BinaryHeap::new(),
+            next_sequence: 0,
+        }
+    }
+
+    pub(super) fn push(&mut self, message: T) {
+        let priority = priority_of(&message);
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.heap.push(Entry {
+            message,
+            priority,
+            sequence,
+        });
+    }
+
+    pub(super) fn pop(&mut self) -> Option<T> {
+        self.heap.pop().map(|entry| entry.message)
+    }
+}
+
+#[derive(Debug)]
+struct Entry<T> {
+    message: T,
+    priority: Priority,
+    sequence: u64,
+}
+
+impl<T> PartialEq for Entry<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.priority == other.priority && self.sequence == other.sequence
+    }
+}
+
+impl<T> Eq for Entry<T> {}
+
+impl<T> PartialOrd for Entry<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Entry<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Higher priority first; for a tie, the earlier (lower) sequence
+        // number should come out of the max-heap first, so it compares as
+        // the greater element.
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+
+/// Wraps a factory component's `Input` with an explicit priority.
+///
+/// A component opts into priority scheduling for its input by using
+/// `PrioritizedInput<Msg>` as its `FactoryComponent::Input`; everything else
+/// keeps its existing FIFO behavior, since [`priority_of`] falls back to
+/// [`DEFAULT_PRIORITY`] for any `Input` type that isn't this wrapper.
+#[derive(Debug, Clone)]
+pub struct PrioritizedInput<T> {
+    message: T,
+    priority: Priority,
+}
+
+impl<T> PrioritizedInput<T> {
+    /// Unwraps the inner message, discarding its priority.
+    pub fn into_inner(self) -> T {
+        self.message
+    }
+
+    /// The inner message.
+    pub fn inner(&self) -> &T {
+        &self.message
+    }
+}
+
+impl<T> WithPriority for PrioritizedInput<T> {
+    fn priority(&self) -> Priority {
+        self.priority
+    }
+}
+
+/// Adds a priority-aware send to `Sender<PrioritizedInput<T>>`, e.g. for
+/// cancellation or resize messages that should jump a backlog of
+/// lower-priority updates. `PrioritizedInput` has no public constructor, so
+/// this is the only way to send one; ordinary messages are sent with
+/// `send_with_priority(message, DEFAULT_PRIORITY)` rather than a plain `send`.
+pub trait PrioritySender<T> {
+    /// Sends `message` to be handled ahead of any lower-priority backlog.
+    fn send_with_priority(
+        &self,
+        message: T,
+        priority: Priority,
+    ) -> Result<(), flume::SendError<PrioritizedInput<T>>>;
+}
+
+impl<T> PrioritySender<T> for crate::Sender<PrioritizedInput<T>> {
+    fn send_with_priority(
+        &self,
+        message: T,
+        priority: Priority,
+    ) -> Result<(), flume::SendError<PrioritizedInput<T>>> {
+        self.send(PrioritizedInput { message, priority })
+    }
+}
@@ -0,0 +1,45 @@
+/// A read handle onto a factory component's latest [`FactoryComponent::State`](super::FactoryComponent),
+/// obtained from [`FactoryHandle::subscribe_state`](super::FactoryHandle::subscribe_state).
+///
+/// Modeled on [`tokio::sync::watch`]: only the most recent state is kept, so
+/// a receiver that attached late (or missed several updates while it was
+/// busy) still sees the current value rather than a backlog of stale ones.
+#[derive(Debug)]
+pub struct StateReceiver<T> {
+    inner: tokio::sync::watch::Receiver<T>,
+    attached: bool,
+}
+
+impl<T: Clone> StateReceiver<T> {
+    pub(super) fn new(inner: tokio::sync::watch::Receiver<T>) -> Self {
+        Self {
+            inner,
+            attached: false,
+        }
+    }
+
+    /// Returns the current state. The first call returns immediately with
+    /// the current state; every call after waits for (and coalesces) the next
+    /// change. Once the component's service has shut down and its state
+    /// sender is gone, every call returns the last state immediately.
+    pub async fn recv(&mut self) -> T {
+        if self.attached {
+            // Ignore a closed channel (the component was terminated): the
+            // last value borrowed below is still valid to hand back.
+            let _ = self.inner.changed().await;
+        } else {
+            self.attached = true;
+        }
+
+        self.inner.borrow_and_update().clone()
+    }
+}
+
+impl<T> Clone for StateReceiver<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            attached: self.attached,
+        }
+    }
+}